@@ -0,0 +1,25 @@
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+/// Runtime configuration, populated from the environment at startup.
+pub static CONFIG: Lazy<Config> = Lazy::new(Config::from_env);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub domain: String,
+    pub port: u16,
+    pub relays: Vec<String>,
+    pub xmpp_username: String,
+    pub xmpp_password: String,
+    pub xmpp_chat_server: String,
+    /// Selects the receive backend on federations exposing both lightning
+    /// modules: `"v2"` uses the LNv2 contract-based flow, anything else (or
+    /// unset) falls back to LNv1.
+    pub receive_version: Option<String>,
+}
+
+impl Config {
+    fn from_env() -> Self {
+        envy::from_env::<Config>().expect("failed to load config from environment")
+    }
+}
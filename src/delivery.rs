@@ -0,0 +1,122 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use fedimint_core::task::spawn;
+use nostr::prelude::*;
+use nostr::secp256k1::XOnlyPublicKey;
+use nostr::{EventBuilder, Kind};
+use serde_json::json;
+use tracing::{error, info};
+use xmpp::{parsers::message::MessageType, Jid};
+
+use crate::{
+    config::CONFIG,
+    model::delivery::{Delivery, DeliveryBmc},
+    state::AppState,
+    utils::{create_xmpp_client, now},
+};
+
+// How long a spent-notes reservation is valid for before we must re-issue it.
+const NOTE_TTL: Duration = Duration::from_secs(604800);
+// Base backoff between delivery attempts; doubled on every failure.
+const BACKOFF_BASE_SECS: i64 = 30;
+const BACKOFF_MAX_SECS: i64 = 3600;
+
+/// Spawn the durable delivery worker. It drains the delivery outbox, encrypting
+/// each ecash payload as a NIP-59 gift wrap and fanning it out over both the
+/// nostr and xmpp channels, retrying with exponential backoff until the primary
+/// nostr channel has accepted the message.
+pub fn spawn_delivery_worker(state: AppState) {
+    spawn("ecash delivery outbox", async move {
+        loop {
+            if let Err(e) = drain(&state).await {
+                error!("delivery worker error: {e}");
+            }
+            fedimint_core::task::sleep(Duration::from_secs(5)).await;
+        }
+    });
+}
+
+async fn drain(state: &AppState) -> Result<()> {
+    let due = DeliveryBmc::list_due(&state.mm, now()).await?;
+    for delivery in due {
+        if let Err(e) = attempt(state, &delivery).await {
+            let next = now()
+                + (BACKOFF_BASE_SECS << delivery.attempts.min(7)).min(BACKOFF_MAX_SECS);
+            error!(
+                "delivery {} attempt {} failed: {e}; retrying at {next}",
+                delivery.id, delivery.attempts
+            );
+            DeliveryBmc::backoff(&state.mm, delivery.id, next).await?;
+        } else {
+            DeliveryBmc::mark_delivered(&state.mm, delivery.id).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn attempt(state: &AppState, delivery: &Delivery) -> Result<()> {
+    let notes = reissue_if_expired(state, delivery).await?;
+    let payload = json!({
+        "operationId": delivery.op_id,
+        "amount": delivery.amount,
+        "notes": notes,
+    })
+    .to_string();
+
+    // The gift-wrapped nostr DM is the primary channel carrying the ecash; it
+    // must be accepted before the row clears. XMPP is best-effort — many
+    // recipients have no XMPP account — so its failure is logged but does not
+    // gate delivery or force the nostr wrap to be re-published forever.
+    send_gift_wrap(state, &delivery.pubkey, &payload).await?;
+    if let Err(e) = send_xmpp_msg(&delivery.name, &payload).await {
+        error!("best-effort xmpp delivery for {} failed: {e}", delivery.op_id);
+    }
+    info!("delivered ecash for operation {}", delivery.op_id);
+    Ok(())
+}
+
+/// Re-issue the payout only once its reservation has actually expired, so a
+/// long-offline recipient still receives a spendable payload without needlessly
+/// refreshing one that is still valid on every retry. The refresh goes through
+/// the [`PaymentProcessor`] so non-Fedimint backends can define their own
+/// payload semantics.
+async fn reissue_if_expired(state: &AppState, delivery: &Delivery) -> Result<String> {
+    if now() < delivery.notes_expiry {
+        return Ok(delivery.notes.clone());
+    }
+
+    let fresh = state
+        .processor
+        .refresh_payout(delivery.amount as u64, delivery.notes.clone())
+        .await?;
+    DeliveryBmc::reissue(
+        &state.mm,
+        delivery.id,
+        fresh.payload.clone(),
+        now() + NOTE_TTL.as_secs() as i64,
+    )
+    .await?;
+    Ok(fresh.payload)
+}
+
+/// Wrap the payload as a NIP-44 encrypted rumor inside a NIP-59 gift wrap so the
+/// ecash is forward-secret and the recipient metadata is hidden, then publish it.
+async fn send_gift_wrap(state: &AppState, pubkey: &str, payload: &str) -> Result<()> {
+    let receiver = XOnlyPublicKey::from_str(pubkey)?;
+    let keys = state.nostr.keys().await;
+    let rumor = EventBuilder::new(Kind::PrivateDirectMessage, payload, []).to_unsigned_event(keys.public_key());
+    let gift_wrap = EventBuilder::gift_wrap(&keys, &receiver, rumor, None)?;
+    state.nostr.send_event(gift_wrap).await?;
+    Ok(())
+}
+
+async fn send_xmpp_msg(name: &str, payload: &str) -> Result<()> {
+    let mut xmpp_client = create_xmpp_client().await?;
+    let recipient = xmpp::BareJid::new(&format!("{name}@{}", CONFIG.xmpp_chat_server))?;
+    xmpp_client
+        .send_message(Jid::Bare(recipient), MessageType::Chat, "en", payload)
+        .await;
+    Ok(())
+}
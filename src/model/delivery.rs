@@ -0,0 +1,67 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::ModelManager;
+
+/// A pending ecash delivery. Every settled payment enqueues one of these so the
+/// notes survive the recipient being offline or the relay dropping the message;
+/// a background worker drains the queue until the delivery is acknowledged.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: i32,
+    pub op_id: String,
+    pub pubkey: String,       // recipient nostr pubkey
+    pub name: String,         // recipient name, used for the xmpp path
+    pub amount: i64,
+    pub notes: String,        // serialized OOBNotes
+    pub notes_expiry: i64,    // unix seconds the reserved notes are valid until
+    pub attempts: i32,
+    pub next_retry: i64,      // unix seconds
+    pub delivered: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeliveryForCreate {
+    pub op_id: String,
+    pub pubkey: String,
+    pub name: String,
+    pub amount: i64,
+    pub notes: String,
+}
+
+pub struct DeliveryBmc;
+
+impl DeliveryBmc {
+    pub async fn create(mm: &ModelManager, delivery: DeliveryForCreate) -> Result<i32> {
+        mm.insert_delivery(delivery).await
+    }
+
+    /// Deliveries that are not yet delivered and whose `next_retry` has elapsed.
+    pub async fn list_due(mm: &ModelManager, now: i64) -> Result<Vec<Delivery>> {
+        mm.list_due_deliveries(now).await
+    }
+
+    /// Record a failed attempt, bumping the attempt count and scheduling the next
+    /// retry with exponential backoff.
+    pub async fn backoff(mm: &ModelManager, id: i32, next_retry: i64) -> Result<Delivery> {
+        mm.backoff_delivery(id, next_retry).await
+    }
+
+    /// Replace the serialized notes for a delivery whose ecash expired and had to
+    /// be re-issued, extending the expiry to the new reservation.
+    pub async fn reissue(
+        mm: &ModelManager,
+        id: i32,
+        notes: String,
+        notes_expiry: i64,
+    ) -> Result<Delivery> {
+        mm.reissue_delivery(id, notes, notes_expiry).await
+    }
+
+    /// Mark the delivery as sent once the nostr gift wrap was accepted, so it is no
+    /// longer drained; the best-effort xmpp send may still have failed. Note this
+    /// is a send confirmation, not a recipient acknowledgement.
+    pub async fn mark_delivered(mm: &ModelManager, id: i32) -> Result<()> {
+        mm.mark_delivery_delivered(id).await
+    }
+}
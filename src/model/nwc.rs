@@ -0,0 +1,44 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::ModelManager;
+
+/// A persisted NIP-47 connection minted for a registration. `service_secret` is
+/// the wallet-side key this service signs and decrypts with; `client_pubkey` is
+/// the connection secret's public key, the only sender we accept requests from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NwcConnectionRecord {
+    pub id: i32,
+    pub name: String,
+    pub service_secret: String, // hex secret key
+    pub client_pubkey: String,  // hex x-only public key
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NwcConnectionForCreate {
+    pub name: String,
+    pub service_secret: String,
+    pub client_pubkey: String,
+}
+
+pub struct NwcConnectionBmc;
+
+impl NwcConnectionBmc {
+    pub async fn create(mm: &ModelManager, conn: NwcConnectionForCreate) -> Result<i32> {
+        mm.insert_nwc_connection(conn).await
+    }
+
+    pub async fn list(mm: &ModelManager) -> Result<Vec<NwcConnectionRecord>> {
+        mm.list_nwc_connections().await
+    }
+
+    /// Look up a connection by its service public key, so a request can be routed
+    /// even if the connection was registered after the listener started.
+    pub async fn get_by_service_pubkey(
+        mm: &ModelManager,
+        service_pubkey: &str,
+    ) -> Result<NwcConnectionRecord> {
+        mm.get_nwc_connection_by_service_pubkey(service_pubkey)
+            .await
+    }
+}
@@ -0,0 +1,318 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::Result;
+use fedimint_core::task::spawn;
+use nostr::nips::nip47::{
+    ErrorCode, GetInfoResponseResult, ListTransactionsRequestParams, LookupInvoiceRequestParams,
+    MakeInvoiceRequestParams, Method, NIP47Error, Request, RequestParams, Response,
+    ResponseResult, TransactionType, LookupInvoiceResponseResult,
+};
+use nostr::prelude::*;
+use nostr::{Filter, Keys, Kind, Timestamp};
+use tracing::error;
+
+use crate::{
+    config::CONFIG,
+    model::{
+        invoice::{Invoice, InvoiceBmc, InvoiceForCreate},
+        nip05relays::Nip05RelaysBmc,
+        nwc::{NwcConnectionBmc, NwcConnectionForCreate, NwcConnectionRecord},
+    },
+    router::handlers::{lnurlp::callback::spawn_invoice_subscription, NameOrPubkey},
+    state::AppState,
+};
+
+/// A single NIP-47 connection minted for a registration. `service` is the
+/// wallet-side key this service signs and decrypts with; `client` is the
+/// connection secret shared with the wallet via the URI — the only sender we
+/// accept requests from.
+#[derive(Clone)]
+pub struct NwcConnection {
+    pub name: String,
+    pub service: Keys,
+    pub client: Keys,
+}
+
+impl NwcConnection {
+    /// Mint a fresh per-registration connection for `name`: a dedicated service
+    /// key and a client connection secret.
+    pub fn generate(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            service: Keys::generate(),
+            client: Keys::generate(),
+        }
+    }
+
+    fn from_record(record: &NwcConnectionRecord) -> Result<Self> {
+        Ok(Self {
+            name: record.name.clone(),
+            service: Keys::parse(&record.service_secret)?,
+            client: Keys::from_public_key(XOnlyPublicKey::from_str(&record.client_pubkey)?),
+        })
+    }
+
+    /// The `nostr+walletconnect://` URI handed to the wallet: the service pubkey,
+    /// a relay we listen on, and the connection secret.
+    pub fn uri(&self) -> Result<String> {
+        let relay = CONFIG
+            .relays
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("no relay configured"))?;
+        Ok(format!(
+            "nostr+walletconnect://{}?relay={}&secret={}",
+            self.service.public_key(),
+            relay,
+            self.client.secret_key()?.display_secret()
+        ))
+    }
+}
+
+/// Mint and persist a NIP-47 connection for `name`, returning the
+/// `nostr+walletconnect://` URI the wallet uses to connect.
+pub async fn register(state: &AppState, name: &str) -> Result<String> {
+    let conn = NwcConnection::generate(name);
+    NwcConnectionBmc::create(
+        &state.mm,
+        NwcConnectionForCreate {
+            name: name.to_string(),
+            service_secret: conn.service.secret_key()?.display_secret().to_string(),
+            client_pubkey: conn.client.public_key().to_string(),
+        },
+    )
+    .await?;
+    conn.uri()
+}
+
+// How often the listener re-subscribes so connections registered after startup
+// are picked up without a restart.
+const SUBSCRIPTION_REFRESH: Duration = Duration::from_secs(30);
+
+/// Listen for NIP-47 requests addressed to any registered connection's service
+/// key, decrypt them, and drive the lightning address programmatically: create
+/// and look up invoices and serve transaction history from [`InvoiceBmc`].
+///
+/// The subscription filter is refreshed periodically so connections minted by
+/// [`register`] at runtime are served without restarting the process, and each
+/// request is routed to its connection by re-querying the database.
+pub fn spawn_nwc_service(state: AppState) {
+    // Keep the subscription filter in sync with the set of registered service
+    // keys as new connections are minted.
+    let refresh_state = state.clone();
+    spawn("nwc subscription refresh", async move {
+        loop {
+            match NwcConnectionBmc::list(&refresh_state.mm).await {
+                Ok(records) => {
+                    let service_pubkeys: Vec<XOnlyPublicKey> = records
+                        .iter()
+                        .filter_map(|r| NwcConnection::from_record(r).ok())
+                        .map(|c| c.service.public_key())
+                        .collect();
+                    let filter = Filter::new()
+                        .kind(Kind::WalletConnectRequest)
+                        .pubkeys(service_pubkeys)
+                        .since(Timestamp::now());
+                    if let Err(e) = refresh_state.nostr.subscribe(vec![filter], None).await {
+                        error!("failed to subscribe to nwc requests: {e}");
+                    }
+                }
+                Err(e) => error!("failed to load nwc connections: {e}"),
+            }
+            fedimint_core::task::sleep(SUBSCRIPTION_REFRESH).await;
+        }
+    });
+
+    spawn("nwc service", async move {
+        let mut notifications = state.nostr.notifications();
+        while let Ok(notification) = notifications.recv().await {
+            if let RelayPoolNotification::Event { event, .. } = notification {
+                if event.kind != Kind::WalletConnectRequest {
+                    continue;
+                }
+                if let Err(e) = handle_request(&state, &event).await {
+                    error!("nwc request failed: {e}");
+                }
+            }
+        }
+    });
+}
+
+async fn handle_request(state: &AppState, event: &Event) -> Result<()> {
+    // Route to the connection the request is addressed to, re-querying so a
+    // connection registered after startup is still found.
+    let service_pubkey = event
+        .tags
+        .iter()
+        .find_map(|t| match t.as_vec() {
+            [k, v, ..] if k == "p" => Some(v.clone()),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("nwc request missing p tag"))?;
+    let record = NwcConnectionBmc::get_by_service_pubkey(&state.mm, &service_pubkey).await?;
+    let conn = NwcConnection::from_record(&record)?;
+
+    // Only accept requests signed by this connection's client secret.
+    if event.pubkey != conn.client.public_key() {
+        anyhow::bail!("nwc request from unauthorized pubkey");
+    }
+    let service = &conn.service;
+    let request = Request::from_event(service, event)?;
+    let result = match request.params {
+        RequestParams::MakeInvoice(params) => make_invoice(state, &conn, params).await,
+        RequestParams::LookupInvoice(params) => lookup_invoice(state, params).await,
+        RequestParams::ListTransactions(params) => list_transactions(state, params).await,
+        RequestParams::GetInfo => get_info(service),
+        _ => Err(NIP47Error {
+            code: ErrorCode::NotImplemented,
+            message: "method not supported".to_string(),
+        }),
+    };
+
+    let response = match result {
+        Ok(result) => Response {
+            result_type: request.method,
+            error: None,
+            result: Some(result),
+        },
+        Err(error) => Response {
+            result_type: request.method,
+            error: Some(error),
+            result: None,
+        },
+    };
+
+    // Encrypt the response back to the requesting connection per NIP-47.
+    let reply = EventBuilder::new(
+        Kind::WalletConnectResponse,
+        nip04::encrypt(service.secret_key()?, &event.pubkey, response.as_json())?,
+        [Tag::event(event.id), Tag::public_key(event.pubkey)],
+    )
+    .to_event(service)?;
+    state.nostr.send_event(reply).await?;
+    Ok(())
+}
+
+async fn make_invoice(
+    state: &AppState,
+    conn: &NwcConnection,
+    params: MakeInvoiceRequestParams,
+) -> Result<ResponseResult, NIP47Error> {
+    let description = params.description.unwrap_or_default();
+    let (op_id, pr) = state
+        .processor
+        .create_invoice(params.amount, description)
+        .await
+        .map_err(internal)?;
+
+    let id = InvoiceBmc::create(
+        &state.mm,
+        InvoiceForCreate {
+            op_id: op_id.to_string(),
+            amount: params.amount as i64,
+            bolt11: pr.clone(),
+            zap_request: None,
+            payer_pubkey: None,
+        },
+    )
+    .await
+    .map_err(internal)?;
+
+    // Drive the same settle + delivery path the LNURL callback uses, so invoices
+    // created over NWC are actually settled and the ecash is delivered to the
+    // registered user rather than sitting unpaid forever.
+    let nip05relays = Nip05RelaysBmc::get_by(&state.mm, NameOrPubkey::Name, &conn.name)
+        .await
+        .map_err(internal)?;
+    let subscription = state
+        .processor
+        .subscribe_settlement(op_id)
+        .await
+        .map_err(internal)?;
+    spawn_invoice_subscription(state.clone(), id, nip05relays, subscription, pr.clone(), None).await;
+
+    Ok(ResponseResult::MakeInvoice(LookupInvoiceResponseResult {
+        invoice: Some(pr),
+        ..Default::default()
+    }))
+}
+
+async fn lookup_invoice(
+    state: &AppState,
+    params: LookupInvoiceRequestParams,
+) -> Result<ResponseResult, NIP47Error> {
+    let bolt11 = params
+        .invoice
+        .ok_or_else(|| NIP47Error {
+            code: ErrorCode::Other,
+            message: "invoice parameter required".to_string(),
+        })?;
+    let invoice = InvoiceBmc::get_by_bolt11(&state.mm, &bolt11)
+        .await
+        .map_err(|_| NIP47Error {
+            code: ErrorCode::NotFound,
+            message: "invoice not found".to_string(),
+        })?;
+    Ok(ResponseResult::LookupInvoice(to_transaction(&invoice)))
+}
+
+async fn list_transactions(
+    state: &AppState,
+    params: ListTransactionsRequestParams,
+) -> Result<ResponseResult, NIP47Error> {
+    let invoices = InvoiceBmc::list(&state.mm).await.map_err(internal)?;
+    let transactions = invoices
+        .iter()
+        .filter(|i| !params.unpaid || !i.settled)
+        .map(to_transaction)
+        .collect();
+    Ok(ResponseResult::ListTransactions(transactions))
+}
+
+fn get_info(service: &Keys) -> Result<ResponseResult, NIP47Error> {
+    Ok(ResponseResult::GetInfo(GetInfoResponseResult {
+        alias: CONFIG.domain.clone(),
+        color: String::new(),
+        pubkey: service.public_key().to_string(),
+        network: "bitcoin".to_string(),
+        block_height: 0,
+        block_hash: String::new(),
+        methods: vec![
+            Method::MakeInvoice.to_string(),
+            Method::LookupInvoice.to_string(),
+            Method::ListTransactions.to_string(),
+            Method::GetInfo.to_string(),
+        ],
+    }))
+}
+
+fn to_transaction(invoice: &Invoice) -> LookupInvoiceResponseResult {
+    LookupInvoiceResponseResult {
+        transaction_type: Some(TransactionType::Incoming),
+        invoice: Some(invoice.bolt11.clone()),
+        amount: invoice.amount as u64,
+        settled_at: if invoice.settled {
+            invoice.settled_at.map(|t| t as u64)
+        } else {
+            None
+        },
+        preimage: invoice.preimage.clone(),
+        ..Default::default()
+    }
+}
+
+fn internal(e: anyhow::Error) -> NIP47Error {
+    NIP47Error {
+        code: ErrorCode::Internal,
+        message: e.to_string(),
+    }
+}
+
+impl std::fmt::Debug for NwcConnection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NwcConnection")
+            .field("service", &self.service.public_key())
+            .field("client", &self.client.public_key())
+            .finish()
+    }
+}
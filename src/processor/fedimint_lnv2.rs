@@ -0,0 +1,116 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use fedimint_client::ClientHandleArc;
+use fedimint_core::core::OperationId;
+use fedimint_core::Amount;
+use fedimint_lnv2_client::{FinalReceiveOperationState, LightningClientModule, ReceiveOperationState};
+use fedimint_mint_client::{MintClientModule, OOBNotes};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+
+use super::{Payout, PaymentProcessor, SettlementState};
+
+// Invoices are valid for a day; notes stay reserved for a week afterwards.
+const INVOICE_EXPIRY: Duration = Duration::from_secs(86400);
+const NOTE_TTL: Duration = Duration::from_secs(604800);
+
+/// [`PaymentProcessor`] backed by the Fedimint LNv2 client. Invoices are backed
+/// by incoming contracts with deterministic gateway selection, unlocking the
+/// improved multi-gateway fallback and receive state machine over LNv1.
+#[derive(Clone)]
+pub struct FedimintLnV2Processor {
+    fm: ClientHandleArc,
+}
+
+impl FedimintLnV2Processor {
+    pub fn new(fm: ClientHandleArc) -> Arc<Self> {
+        Arc::new(Self { fm })
+    }
+}
+
+#[async_trait]
+impl PaymentProcessor for FedimintLnV2Processor {
+    async fn create_invoice(
+        &self,
+        amount: u64,
+        description: String,
+    ) -> anyhow::Result<(OperationId, String)> {
+        let ln = self.fm.get_first_module::<LightningClientModule>();
+        // Deterministic gateway selection: sort the federation's advertised
+        // gateways by public key and try them in that order, so the same
+        // federation state always yields the same gateway while still falling
+        // back to the next one when a receive through a gateway fails.
+        let mut gateways = ln.list_gateways().await;
+        gateways.sort_by_key(|g| g.gateway.to_string());
+        let mut last_err = anyhow::anyhow!("no lnv2 gateway available");
+        for g in gateways {
+            match ln
+                .receive(
+                    Amount { msats: amount },
+                    INVOICE_EXPIRY,
+                    description.clone().into(),
+                    g.gateway,
+                    (),
+                )
+                .await
+            {
+                Ok((op_id, invoice)) => return Ok((op_id, invoice.to_string())),
+                Err(e) => last_err = e.into(),
+            }
+        }
+        Err(last_err)
+    }
+
+    async fn subscribe_settlement(
+        &self,
+        op_id: OperationId,
+    ) -> anyhow::Result<BoxStream<'static, SettlementState>> {
+        let ln = self.fm.get_first_module::<LightningClientModule>();
+        let subscription = ln
+            .subscribe_receive_operation_state_updates(op_id)
+            .await
+            .expect("subscribing to a just created operation can't fail");
+        let stream = subscription.into_stream().map(|state| match state {
+            // The incoming contract is funded and then claimed into ecash.
+            ReceiveOperationState::Claiming | ReceiveOperationState::Funded => {
+                SettlementState::Pending
+            }
+            ReceiveOperationState::Pending => SettlementState::Pending,
+            // The claimed incoming contract reveals the payment preimage.
+            ReceiveOperationState::Final(FinalReceiveOperationState::Claimed(preimage)) => {
+                SettlementState::Claimed {
+                    preimage: Some(hex::encode(preimage)),
+                }
+            }
+            ReceiveOperationState::Final(FinalReceiveOperationState::Expired) => {
+                SettlementState::Canceled("invoice expired".to_string())
+            }
+            ReceiveOperationState::Final(FinalReceiveOperationState::Failure) => {
+                SettlementState::Canceled("receive failed".to_string())
+            }
+        });
+        Ok(stream.boxed())
+    }
+
+    async fn payout(&self, amount: u64) -> anyhow::Result<Payout> {
+        let mint = self.fm.get_first_module::<MintClientModule>();
+        let (op_id, notes) = mint
+            .spend_notes(Amount::from_msats(amount), NOTE_TTL, ())
+            .await?;
+        Ok(Payout {
+            op_id,
+            payload: notes.to_string(),
+        })
+    }
+
+    async fn refresh_payout(&self, amount: u64, payload: String) -> anyhow::Result<Payout> {
+        // Redeem the expired notes back into the wallet, then spend a fresh set.
+        let mint = self.fm.get_first_module::<MintClientModule>();
+        let notes = OOBNotes::from_str(&payload)?;
+        mint.reissue_external_notes(notes, ()).await?;
+        self.payout(amount).await
+    }
+}
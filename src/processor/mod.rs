@@ -0,0 +1,69 @@
+pub mod fedimint;
+pub mod fedimint_lnv2;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fedimint_client::ClientHandleArc;
+use fedimint_core::core::OperationId;
+use futures::stream::BoxStream;
+
+pub use fedimint::FedimintProcessor;
+pub use fedimint_lnv2::FedimintLnV2Processor;
+
+use crate::config::CONFIG;
+
+/// Settlement state of a receive, normalized across backends so the callback
+/// subscription does not depend on any one module's state machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SettlementState {
+    Pending,
+    /// The payment was claimed; carries the payment preimage when the backend
+    /// surfaces it (LNv2 contracts do, LNv1 does not).
+    Claimed { preimage: Option<String> },
+    Canceled(String),
+}
+
+/// The spendable payout handed to the delivery outbox once a receive settles.
+#[derive(Clone, Debug)]
+pub struct Payout {
+    pub op_id: OperationId,
+    /// Backend-specific serialized payload (e.g. fedimint OOBNotes).
+    pub payload: String,
+}
+
+/// Receive backend: creates invoices, reports their settlement, and pays out the
+/// received value. A [`FedimintProcessor`] is the default; operators can plug in
+/// alternatives such as LNbits or a CLN node.
+#[async_trait]
+pub trait PaymentProcessor: Send + Sync {
+    /// Create a BOLT11 invoice for `amount` msats, returning the operation id
+    /// used to track it and the invoice string.
+    async fn create_invoice(&self, amount: u64, description: String)
+        -> anyhow::Result<(OperationId, String)>;
+
+    /// Stream settlement updates for a previously created invoice.
+    async fn subscribe_settlement(
+        &self,
+        op_id: OperationId,
+    ) -> anyhow::Result<BoxStream<'static, SettlementState>>;
+
+    /// Pay out `amount` msats of received value, producing a payload to deliver
+    /// to the recipient.
+    async fn payout(&self, amount: u64) -> anyhow::Result<Payout>;
+
+    /// Re-issue an expired payout of `amount` msats, given its previous
+    /// serialized payload, producing a fresh payload. Backends whose payloads do
+    /// not expire may simply return the existing one.
+    async fn refresh_payout(&self, amount: u64, payload: String) -> anyhow::Result<Payout>;
+}
+
+/// Select the receive backend for `fm` based on `CONFIG.receive_version`. On
+/// federations exposing both lightning modules operators can choose `"v2"` for
+/// the LNv2 contract-based flow; anything else falls back to LNv1.
+pub fn from_config(fm: ClientHandleArc) -> Arc<dyn PaymentProcessor> {
+    match CONFIG.receive_version.as_deref() {
+        Some("v2") => FedimintLnV2Processor::new(fm),
+        _ => FedimintProcessor::new(fm),
+    }
+}
@@ -1,33 +1,30 @@
-use std::{str::FromStr, time::Duration};
-
 use anyhow::Result;
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
-use fedimint_client::oplog::UpdateStreamOrOutcome;
-use fedimint_core::{core::OperationId, task::spawn, Amount};
-use fedimint_ln_client::{LightningClientModule, LnReceiveState};
-use fedimint_mint_client::{MintClientModule, OOBNotes};
+use fedimint_core::task::spawn;
+use futures::stream::BoxStream;
 use futures::StreamExt;
-use nostr::secp256k1::XOnlyPublicKey;
+use nostr::prelude::*;
+use nostr::{Event, EventBuilder, Kind, Tag};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use tracing::{error, info};
 use url::Url;
-use xmpp::{parsers::message::MessageType, Jid};
 
 use crate::{
     config::CONFIG,
     error::AppError,
     model::{
+        delivery::{DeliveryBmc, DeliveryForCreate},
         invoice::{InvoiceBmc, InvoiceForCreate},
         nip05relays::Nip05RelaysBmc,
     },
+    processor::SettlementState,
     router::handlers::{nostr::Nip05Relays, NameOrPubkey},
     state::AppState,
-    utils::{create_xmpp_client, empty_string_as_none},
+    utils::empty_string_as_none,
 };
 
 use super::LnurlStatus;
@@ -42,6 +39,8 @@ pub struct LnurlCallbackParams {
     pub comment: Option<String>, // Optional parameter to pass the LN WALLET user's comment to LN SERVICE
     #[serde(default, deserialize_with = "empty_string_as_none")]
     pub proofofpayer: Option<String>, // Optional ephemeral secp256k1 public key generated by payer
+    #[serde(default, deserialize_with = "empty_string_as_none")]
+    pub nostr: Option<String>, // Optional URL-encoded NIP-57 kind-9734 zap request event
 }
 
 #[derive(Serialize, Deserialize)]
@@ -82,16 +81,16 @@ pub async fn handle_callback(
     }
     let nip05relays = Nip05RelaysBmc::get_by(&state.mm, NameOrPubkey::Name, &username).await?;
 
-    let ln = state.fm.get_first_module::<LightningClientModule>();
-    let (op_id, pr) = ln
-        .create_bolt11_invoice(
-            Amount {
-                msats: params.amount,
-            },
-            "test invoice".to_string(),
-            None,
-            (),
-        )
+    // if a NIP-57 zap request is attached, validate it before creating the invoice
+    let zap_request = params
+        .nostr
+        .as_deref()
+        .map(|n| validate_zap_request(n, params.amount))
+        .transpose()?;
+
+    let (op_id, pr) = state
+        .processor
+        .create_invoice(params.amount, "test invoice".to_string())
         .await?;
 
     // insert invoice into db for later verification
@@ -100,18 +99,17 @@ pub async fn handle_callback(
         InvoiceForCreate {
             op_id: op_id.to_string(),
             amount: params.amount as i64,
-            bolt11: pr.to_string(),
+            bolt11: pr.clone(),
+            zap_request: zap_request.as_ref().map(|e| e.as_json()),
+            payer_pubkey: params.proofofpayer.clone(),
         },
     )
     .await?;
 
     // create subscription to operation
-    let subscription = ln
-        .subscribe_ln_receive(op_id)
-        .await
-        .expect("subscribing to a just created operation can't fail");
+    let subscription = state.processor.subscribe_settlement(op_id).await?;
 
-    spawn_invoice_subscription(state, id, nip05relays, subscription).await;
+    spawn_invoice_subscription(state, id, nip05relays, subscription, pr.clone(), zap_request).await;
 
     let verify_url = format!(
         "http://{}:{}/lnurlp/{}/verify/{}",
@@ -133,96 +131,169 @@ pub async fn handle_callback(
     Ok(Json(res))
 }
 
-async fn spawn_invoice_subscription(
+pub(crate) async fn spawn_invoice_subscription(
     state: AppState,
     id: i32,
     nip05relays: Nip05Relays,
-    subscription: UpdateStreamOrOutcome<LnReceiveState>,
+    subscription: BoxStream<'static, SettlementState>,
+    bolt11: String,
+    zap_request: Option<Event>,
 ) {
     spawn("waiting for invoice being paid", async move {
-        let mut stream = subscription.into_stream();
+        let mut stream = subscription;
         while let Some(op_state) = stream.next().await {
             match op_state {
-                LnReceiveState::Canceled { reason } => {
+                SettlementState::Canceled(reason) => {
                     error!("Payment canceled, reason: {:?}", reason);
                     break;
                 }
-                LnReceiveState::Claimed => {
+                SettlementState::Claimed { preimage } => {
                     info!("Payment claimed");
-                    let invoice = InvoiceBmc::settle(&state.mm, id)
+                    let invoice = InvoiceBmc::settle(&state.mm, id, preimage.clone())
                         .await
                         .expect("settling invoice can't fail");
+                    if let Some(req) = zap_request.as_ref() {
+                        if let Err(e) =
+                            publish_zap_receipt(&state, req, &bolt11, preimage.as_deref()).await
+                        {
+                            error!("Failed to publish zap receipt: {e}");
+                        }
+                    }
                     notify_user(state, invoice.amount as u64, nip05relays.clone())
                         .await
                         .expect("notifying user can't fail");
                     break;
                 }
-                _ => {}
+                SettlementState::Pending => {}
             }
         }
     });
 }
 
-async fn notify_user(
-    state: AppState,
-    amount: u64,
-    nip05relays: Nip05Relays,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let mint = state.fm.get_first_module::<MintClientModule>();
-    let (operation_id, notes) = mint
-        .spend_notes(Amount::from_msats(amount), Duration::from_secs(604800), ())
-        .await?;
-    send_nostr_dm(&state, &nip05relays, operation_id, amount, notes).await?;
-    Ok(())
+/// Parse and validate a NIP-57 kind-9734 zap request carried in the `nostr`
+/// callback parameter. The request must be a signed kind-9734 event with
+/// exactly one `p` tag (and an optional single `e` tag), a `relays` tag, and an
+/// `amount` tag matching the amount the payer asked us to invoice.
+fn validate_zap_request(nostr: &str, amount: u64) -> Result<Event, AppError> {
+    let bad_request = |msg: &str| AppError {
+        error: anyhow::anyhow!("invalid zap request: {msg}"),
+        status: StatusCode::BAD_REQUEST,
+    };
+
+    let event = Event::from_json(nostr).map_err(|_| bad_request("malformed event"))?;
+    event.verify().map_err(|_| bad_request("invalid signature"))?;
+
+    if event.kind != Kind::ZapRequest {
+        return Err(bad_request("not a kind 9734 event"));
+    }
+
+    let p_tags = event.tags.iter().filter(|t| t.kind() == TagKind::P).count();
+    if p_tags != 1 {
+        return Err(bad_request("expected exactly one p tag"));
+    }
+    let e_tags = event.tags.iter().filter(|t| t.kind() == TagKind::E).count();
+    if e_tags > 1 {
+        return Err(bad_request("expected at most one e tag"));
+    }
+
+    if !event
+        .tags
+        .iter()
+        .any(|t| t.kind() == TagKind::Relays)
+    {
+        return Err(bad_request("missing relays tag"));
+    }
+
+    let amount_tag = event
+        .tags
+        .iter()
+        .find_map(|t| match t.as_vec() {
+            [k, v, ..] if k == "amount" => v.parse::<u64>().ok(),
+            _ => None,
+        })
+        .ok_or_else(|| bad_request("missing amount tag"))?;
+    if amount_tag != amount {
+        return Err(bad_request("amount tag does not match invoice amount"));
+    }
+
+    Ok(event)
 }
 
-async fn send_nostr_dm(
+/// Build, sign and publish a NIP-57 kind-9735 zap receipt for a settled zap
+/// request to the union of the request's `relays` tag and our configured relays.
+async fn publish_zap_receipt(
     state: &AppState,
-    nip05relays: &Nip05Relays,
-    operation_id: OperationId,
-    amount: u64,
-    notes: OOBNotes,
+    request: &Event,
+    bolt11: &str,
+    preimage: Option<&str>,
 ) -> Result<()> {
-    state
-        .nostr
-        .send_direct_msg(
-            XOnlyPublicKey::from_str(&nip05relays.pubkey).unwrap(),
-            json!({
-                "operationId": operation_id,
-                "amount": amount,
-                "notes": notes.to_string(),
-            })
-            .to_string(),
-            None,
-        )
-        .await?;
+    let mut tags: Vec<Tag> = request
+        .tags
+        .iter()
+        .filter(|t| matches!(t.kind(), TagKind::P | TagKind::E))
+        .cloned()
+        .collect();
+    tags.push(Tag::Bolt11(bolt11.to_string()));
+    if let Some(preimage) = preimage {
+        tags.push(Tag::Preimage(preimage.to_string()));
+    }
+    tags.push(Tag::Description(request.as_json()));
+
+    let keys = state.nostr.keys().await;
+    let receipt = EventBuilder::new(Kind::ZapReceipt, "", tags).to_event(&keys)?;
+
+    // Publish to the union of the request's relays and our configured relays
+    // transiently, without mutating the shared client's global relay pool with
+    // attacker-controlled URLs. The request's relays are attacker-controlled, so
+    // keep only well-formed ws(s) URLs and cap how many we will dial.
+    let mut relays: Vec<String> = request
+        .tags
+        .iter()
+        .find(|t| t.kind() == TagKind::Relays)
+        .map(|t| {
+            t.as_vec()
+                .iter()
+                .skip(1)
+                .filter(|r| is_valid_relay_url(r))
+                .take(MAX_ZAP_RELAYS)
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    relays.extend(CONFIG.relays.iter().cloned());
+    state.nostr.send_event_to(relays, receipt).await?;
     Ok(())
 }
 
-// TODO: add xmpp to registration
-async fn send_xmpp_msg(
-    nip05relays: &Nip05Relays,
-    operation_id: OperationId,
-    amount: u64,
-    notes: OOBNotes,
-) -> Result<()> {
-    let mut xmpp_client = create_xmpp_client()?;
-    let recipient =
-        xmpp::BareJid::new(&format!("{}@{}", nip05relays.name, CONFIG.xmpp_chat_server))?;
-
-    xmpp_client
-        .send_message(
-            Jid::Bare(recipient),
-            MessageType::Chat,
-            "en",
-            &json!({
-                "operationId": operation_id,
-                "amount": amount,
-                "notes": notes.to_string(),
-            })
-            .to_string(),
-        )
-        .await;
+// Upper bound on how many relays from a zap request we will dial per receipt.
+const MAX_ZAP_RELAYS: usize = 10;
 
+/// A relay URL is acceptable only if it parses and uses the `ws`/`wss` scheme.
+fn is_valid_relay_url(url: &str) -> bool {
+    Url::parse(url)
+        .map(|u| matches!(u.scheme(), "ws" | "wss"))
+        .unwrap_or(false)
+}
+
+// Reserve the ecash and enqueue a durable delivery row. The delivery worker owns
+// the actual send (gift-wrapped nostr DM + xmpp) and retries until acknowledged,
+// so notes are never lost if the recipient is offline.
+async fn notify_user(
+    state: AppState,
+    amount: u64,
+    nip05relays: Nip05Relays,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let payout = state.processor.payout(amount).await?;
+    DeliveryBmc::create(
+        &state.mm,
+        DeliveryForCreate {
+            op_id: payout.op_id.to_string(),
+            pubkey: nip05relays.pubkey,
+            name: nip05relays.name,
+            amount: amount as i64,
+            notes: payout.payload,
+        },
+    )
+    .await?;
     Ok(())
 }
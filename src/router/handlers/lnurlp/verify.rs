@@ -0,0 +1,45 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::AppError,
+    model::invoice::InvoiceBmc,
+    state::AppState,
+};
+
+use super::LnurlStatus;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LnurlVerifyResponse {
+    pub status: LnurlStatus,
+    pub settled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preimage: Option<String>,
+    pub pr: String, // BOLT11 invoice
+    // ephemeral payer pubkey echoed back so a payer can prove the invoice was theirs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proofofpayer: Option<String>,
+}
+
+// LUD-21: wallets poll this to learn whether an invoice has settled without
+// relying on the ecash DM arriving.
+pub async fn handle_verify(
+    Path((_username, op_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<Json<LnurlVerifyResponse>, AppError> {
+    let invoice = InvoiceBmc::get_by_op_id(&state.mm, &op_id).await?;
+
+    let res = LnurlVerifyResponse {
+        status: LnurlStatus::Ok,
+        settled: invoice.settled,
+        preimage: invoice.preimage.filter(|_| invoice.settled),
+        pr: invoice.bolt11,
+        proofofpayer: invoice.payer_pubkey,
+    };
+
+    Ok(Json(res))
+}
@@ -0,0 +1,44 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::CONFIG, error::AppError, state::AppState};
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LnurlWellKnownResponse {
+    pub callback: String,
+    pub max_sendable: u64,
+    pub min_sendable: u64,
+    pub metadata: String,
+    pub comment_allowed: u8,
+    pub tag: String,
+    // NIP-57: advertise that this address accepts zaps and with which key.
+    pub allows_nostr: bool,
+    pub nostr_pubkey: String,
+}
+
+// LUD-06 pay-request metadata, served at /.well-known/lnurlp/{username}.
+pub async fn handle_well_known(
+    Path(username): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<LnurlWellKnownResponse>, AppError> {
+    let metadata = format!("[[\"text/identifier\",\"{username}@{}\"]]", CONFIG.domain);
+    let res = LnurlWellKnownResponse {
+        callback: format!(
+            "http://{}:{}/lnurlp/{}/callback",
+            CONFIG.domain, CONFIG.port, username
+        ),
+        max_sendable: 100_000_000,
+        min_sendable: 1000,
+        metadata,
+        comment_allowed: 255,
+        tag: "payRequest".to_string(),
+        allows_nostr: true,
+        nostr_pubkey: state.nostr.keys().await.public_key().to_string(),
+    };
+
+    Ok(Json(res))
+}
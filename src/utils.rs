@@ -1,3 +1,4 @@
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{fmt::Display, str::FromStr};
 
 use crate::config::CONFIG;
@@ -5,6 +6,14 @@ use anyhow::Result;
 use serde::{de, Deserialize, Deserializer};
 use xmpp::Agent;
 
+/// Current unix time in whole seconds, used for scheduling delivery retries.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_secs() as i64
+}
+
 pub fn empty_string_as_none<'de, D, T>(de: D) -> Result<Option<T>, D::Error>
 where
     D: Deserializer<'de>,